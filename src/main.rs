@@ -1,4 +1,8 @@
-use async_std::{fs::File, io::BufReader, prelude::*};
+use async_std::{
+    fs::{File, OpenOptions},
+    io::{stdin, BufReader},
+    prelude::*,
+};
 use std::{
     collections::BTreeMap,
     error::Error,
@@ -10,8 +14,8 @@ use clap::Clap;
 use dotenv::dotenv;
 use envy::from_env;
 use hmac::{Hmac, Mac, NewMac};
-use log::{error, warn, debug};
-use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use log::{debug, error, warn};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
 use rand::{seq::SliceRandom, thread_rng};
 use serde::Deserialize;
 use serde_json::{from_str, Value};
@@ -52,18 +56,33 @@ const RFC3986_ESCAPES: &AsciiSet = &CONTROLS
 type HmacSha1 = Hmac<Sha1>;
 type AnyResult<T> = Result<T, Box<dyn Error + Send + Sync + 'static>>;
 
-/// Send a raw request to Twitter.
 #[derive(Debug, Clap)]
-struct Arguments {
+enum Arguments {
+    /// Send a raw request to Twitter.
+    Send(SendArguments),
+
+    /// Obtain an access token via the 3-legged PIN-based OAuth flow.
+    Auth,
+}
+
+#[derive(Debug, Clap)]
+struct SendArguments {
     /// Request template file (*.json)
     template_file: String,
 
     /// Overrides some parameters in template file.
     #[clap(short, long = "param")]
     parameters: Vec<String>,
+
+    /// Uploads the file and attaches it to the request as `media_ids`. Repeatable.
+    #[clap(short, long)]
+    media: Vec<String>,
 }
 
-/// 環境変数
+/// 環境変数 (リクエスト送信に必要な一式)
+///
+/// `access_token`/`access_token_secret` は OAuth1 署名 (通常リクエストやメディア
+/// アップロード) にのみ必要で、Bearer トークンのみで済む場合は設定しなくてよい。
 #[derive(Debug, Deserialize)]
 struct Environments {
     #[serde(rename = "twitter_ck")]
@@ -73,10 +92,71 @@ struct Environments {
     consumer_secret: String,
 
     #[serde(rename = "twitter_at")]
-    access_token: String,
+    access_token: Option<String>,
 
     #[serde(rename = "twitter_ats")]
-    access_token_secret: String,
+    access_token_secret: Option<String>,
+}
+
+impl Environments {
+    /// OAuth1 署名に必要なアクセストークンを取り出す。無ければエラーにする。
+    fn require_access_token(&self) -> AnyResult<(&str, &str)> {
+        let access_token = self
+            .access_token
+            .as_deref()
+            .ok_or("twitter_at is required for OAuth1-signed requests")?;
+        let access_token_secret = self
+            .access_token_secret
+            .as_deref()
+            .ok_or("twitter_ats is required for OAuth1-signed requests")?;
+        Ok((access_token, access_token_secret))
+    }
+}
+
+/// 環境変数 (コンシューマーキーのみ。`auth` サブコマンドはアクセストークンをまだ持っていない)
+#[derive(Debug, Deserialize)]
+struct ConsumerEnvironments {
+    #[serde(rename = "twitter_ck")]
+    consumer_key: String,
+
+    #[serde(rename = "twitter_cs")]
+    consumer_secret: String,
+}
+
+/// 認証方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Auth {
+    /// OAuth 1.0a (HMAC-SHA1 署名つきリクエスト)
+    #[serde(rename = "oauth1")]
+    OAuth1,
+
+    /// OAuth 2.0 app-only (Bearer トークン)
+    Bearer,
+}
+
+impl Default for Auth {
+    fn default() -> Self {
+        Auth::OAuth1
+    }
+}
+
+/// パラメーターの送り方
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum Body {
+    /// クエリ文字列 (OAuth1 の署名ベース文字列にも含まれる)
+    Query,
+
+    /// JSON ドキュメント。v2 系のエンドポイント向け。OAuth1 の仕様上、
+    /// JSON ボディは署名ベース文字列に含めない。
+    Json,
+}
+
+impl Default for Body {
+    fn default() -> Self {
+        Body::Query
+    }
 }
 
 /// HTTP メソッド
@@ -110,47 +190,95 @@ impl ToString for Method {
 /// リクエストテンプレートファイル
 #[derive(Debug, Clone, Deserialize)]
 struct Template {
-    /// エンドポイント名
+    /// エンドポイント名。`http://`/`https://` で始まる場合は `base_url`/`version` を
+    /// 無視し、完全な URL として扱う。
     endpoint: Box<str>,
 
+    /// API のベース URL
+    #[serde(default = "default_base_url")]
+    base_url: Box<str>,
+
+    /// API バージョン
+    #[serde(default = "default_version")]
+    version: Box<str>,
+
     /// HTTP メソッド
     method: Method,
 
-    /// クエリパラメーター
+    /// 認証方式
+    #[serde(default)]
+    auth: Auth,
+
+    /// ストリーミングエンドポイントかどうか。true の場合、レスポンスボディを
+    /// 改行区切りの JSON として継続的に読み出す。
+    #[serde(default)]
+    stream: bool,
+
+    /// パラメーターの送り方。`POST`/`PUT` 以外では常にクエリ文字列として扱う。
+    #[serde(default)]
+    body: Body,
+
+    /// リクエストパラメーター。`body` が `Query` ならクエリ文字列として、`Json` なら
+    /// JSON ボディとして送られる。
     parameters: BTreeMap<Box<str>, Value>,
+
+    /// 常にクエリ文字列として送られ、OAuth1 の署名ベース文字列にも含まれるパラメーター。
+    /// `body: json` のテンプレートが、JSON ボディに加えて署名付きのクエリパラメーターも
+    /// 持ちたい場合に使う (`body: query` では `parameters` と合流する)。
+    #[serde(default)]
+    query_parameters: BTreeMap<Box<str>, Value>,
 }
 
-#[async_std::main]
-async fn main() -> AnyResult<()> {
-    pretty_env_logger::init();
-    dotenv().ok();
+fn default_base_url() -> Box<str> {
+    "https://api.twitter.com".into()
+}
 
-    let arguments = Arguments::parse();
-    let environments: Environments = from_env().map_err(|e| {
-        error!("Failed to gather Twitter API key information: {}", e);
-        e
-    })?;
-    debug!("Consumer Key: {}", environments.consumer_key);
-    debug!("Consumer Secret: {}", environments.consumer_secret);
-    debug!("Access Token: {}", environments.access_token);
-    debug!("Access Token Secret: {}", environments.access_token_secret);
+fn default_version() -> Box<str> {
+    "1.1".into()
+}
 
-    let template: Template = {
-        let mut reader = BufReader::new(File::open(&arguments.template_file).await?);
-        let mut json = String::with_capacity(8192);
-        reader.read_to_string(&mut json).await?;
-        from_str(&json).map_err(|e| {
-            error!("Failed to parse template file: {}", e);
-            e
-        })?
-    };
+/// `Template` の `endpoint`/`base_url`/`version` から実際にリクエストする URL を組み立てる。
+/// `endpoint` が `http://`/`https://` で始まる場合は完全な URL としてそのまま使う。
+fn build_endpoint_url(template: &Template) -> String {
+    if template.endpoint.starts_with("http://") || template.endpoint.starts_with("https://") {
+        template.endpoint.to_string()
+    } else {
+        format!(
+            "{}/{}/{}",
+            template.base_url, template.version, template.endpoint
+        )
+    }
+}
+
+/// クエリ文字列/フォームボディ用に `Value` を文字列へエンコードする。
+fn format_param_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => utf8_percent_encode(s, RFC3986_ESCAPES).to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        _ => unreachable!("Invalid data type"),
+    }
+}
 
-    // OAuth パラメーターの生成
+/// OAuth 1.0a の `oauth_*` パラメーター一式を生成し、`oauth_signature` を計算して埋める。
+///
+/// `extra_oauth_params` には `oauth_callback`/`oauth_verifier` など呼び出し元ごとに異なる
+/// パラメーターを渡す。リクエストトークン取得など、まだトークンを持たない場合は
+/// `token`/`token_secret` に空文字列を渡せばよい。
+fn sign_request(
+    method: Method,
+    url: &str,
+    query_params: &BTreeMap<Box<str>, Value>,
+    extra_oauth_params: &[(&'static str, String)],
+    consumer_key: &str,
+    consumer_secret: &str,
+    token: &str,
+    token_secret: &str,
+) -> AnyResult<BTreeMap<&'static str, String>> {
     let mut oauth_params = BTreeMap::new();
     oauth_params.insert("oauth_version", "1.0".to_owned());
-    oauth_params.insert("oauth_signature_method".into(), "HMAC-SHA1".to_owned());
-    oauth_params.insert("oauth_consumer_key", environments.consumer_key.clone());
-    oauth_params.insert("oauth_token", environments.access_token.clone());
+    oauth_params.insert("oauth_signature_method", "HMAC-SHA1".to_owned());
+    oauth_params.insert("oauth_consumer_key", consumer_key.to_owned());
     oauth_params.insert("oauth_nonce", {
         // thread_rng() は cryptographically secure
         let mut rng = thread_rng();
@@ -166,79 +294,337 @@ async fn main() -> AnyResult<()> {
             }
         },
     );
-
-    // 通常パラメーターの生成
-    let mut request_params = template.parameters.clone();
-    for op in &arguments.parameters {
-        let kv: Vec<_> = op.split('=').take(2).collect();
-        match (kv.get(0), kv.get(1)) {
-            (Some(&k), Some(&v)) if k != "" => {
-                request_params.insert(k.into(), v.into());
-            }
-            _ => {
-                warn!("Invalid parameter override detected, skipping...");
-            }
-        }
+    if !token.is_empty() {
+        oauth_params.insert("oauth_token", token.to_owned());
+    }
+    for (key, value) in extra_oauth_params {
+        oauth_params.insert(key, value.clone());
     }
 
-    let mut request_params_str: Vec<_> = request_params
+    let mut params_str: Vec<_> = query_params
         .iter()
-        .map(|(k, v)| {
-            format!(
-                "{}={}",
-                k,
-                match v {
-                    Value::String(s) => utf8_percent_encode(&s, RFC3986_ESCAPES).to_string(),
-                    Value::Number(n) => n.to_string(),
-                    Value::Bool(b) => b.to_string(),
-                    _ => unreachable!("Invalid data type"),
-                }
-            )
-        })
+        .map(|(k, v)| format!("{}={}", k, format_param_value(v)))
         .chain(
             oauth_params
                 .iter()
-                .map(|(k, v)| format!("{}={}", k, utf8_percent_encode(&v, RFC3986_ESCAPES))),
+                .map(|(k, v)| format!("{}={}", k, utf8_percent_encode(v, RFC3986_ESCAPES))),
         )
         .collect();
-    request_params_str.sort();
-
-    // エンドポイント
-    let endpoint_url = format!("https://api.twitter.com/1.1/{}", &template.endpoint);
+    params_str.sort();
 
     // シグネチャの生成
-    let connected_params = request_params_str.join("&");
+    let connected_params = params_str.join("&");
     let signature_base = format!(
         "{}&{}&{}",
-        template.method.to_string(),
-        utf8_percent_encode(&endpoint_url, RFC3986_ESCAPES),
+        method.to_string(),
+        utf8_percent_encode(url, RFC3986_ESCAPES),
         utf8_percent_encode(&connected_params, RFC3986_ESCAPES)
     );
-
-    let signature_key = format!(
-        "{}&{}",
-        &environments.consumer_secret, &environments.access_token_secret
-    );
+    let signature_key = format!("{}&{}", consumer_secret, token_secret);
 
     let mut hmac = HmacSha1::new_varkey(&signature_key.into_bytes()).expect("Should be accepted");
     hmac.update(&signature_base.into_bytes());
-    let hmac_result = hmac.finalize().into_bytes();
-    let encoded_signature = base64_encode(hmac_result);
+    let encoded_signature = base64_encode(hmac.finalize().into_bytes());
     oauth_params.insert("oauth_signature", encoded_signature);
 
     debug!("OAuth parameters");
     for (key, value) in &oauth_params {
         debug!("{}: {}", key, value);
     }
-    debug!("General parameters");
-    for (key, value) in &request_params {
-        debug!("{}: {}", key, value);
-    }
 
-    let oauth_header: Vec<_> = oauth_params
+    Ok(oauth_params)
+}
+
+/// 署名済みの `oauth_*` パラメーターから `Authorization: OAuth ...` ヘッダーの値を組み立てる。
+fn oauth_header(oauth_params: &BTreeMap<&'static str, String>) -> String {
+    let parts: Vec<_> = oauth_params
         .iter()
         .map(|(k, v)| format!("{}=\"{}\"", k, utf8_percent_encode(v, RFC3986_ESCAPES)))
         .collect();
+    format!("OAuth {}", parts.join(", "))
+}
+
+/// `key=value&key=value...` 形式のレスポンスボディをパースする。
+fn parse_form_encoded(body: &str) -> BTreeMap<String, String> {
+    body.split('&')
+        .filter_map(|pair| {
+            let mut it = pair.splitn(2, '=');
+            let key = it.next()?;
+            let value = it.next()?;
+            Some((
+                key.to_owned(),
+                percent_decode_str(value).decode_utf8_lossy().into_owned(),
+            ))
+        })
+        .collect()
+}
+
+/// Bearer トークンを OAuth 2.0 client credentials フローで取得する。
+async fn fetch_bearer_token(consumer_key: &str, consumer_secret: &str) -> AnyResult<String> {
+    let credential = format!(
+        "{}:{}",
+        utf8_percent_encode(consumer_key, RFC3986_ESCAPES),
+        utf8_percent_encode(consumer_secret, RFC3986_ESCAPES),
+    );
+    let basic_auth = base64_encode(credential.into_bytes());
+
+    let mut response = surf::post("https://api.twitter.com/oauth2/token")
+        .header("Authorization", format!("Basic {}", basic_auth))
+        .body_string("grant_type=client_credentials".into())
+        .content_type("application/x-www-form-urlencoded")
+        .await?;
+    let body = response.body_string().await?;
+    let parsed: Value = from_str(&body).map_err(|e| {
+        error!("Failed to parse bearer token response: {}", e);
+        e
+    })?;
+
+    match parsed.get("access_token").and_then(Value::as_str) {
+        Some(token) => Ok(token.to_owned()),
+        None => {
+            error!("Bearer token response did not contain access_token: {}", body);
+            Err("missing access_token in bearer token response".into())
+        }
+    }
+}
+
+/// ストリーミングエンドポイントのレスポンスを 1 レコードずつ読み出し、標準出力へ流す。
+///
+/// Twitter のストリーミング API はレコードを `\r\n` (歴史的には素の `\r`) で区切り、
+/// 接続維持のための空行を挟んでくることがある。読めたレコードは都度パースして出力し、
+/// 壊れたフレームが来ても接続は切らずに警告だけ出す。
+async fn stream_response(response: surf::Response) -> AnyResult<()> {
+    let mut reader = BufReader::new(response);
+    let mut buffer: Vec<u8> = Vec::new();
+    let mut chunk = [0u8; 4096];
+
+    loop {
+        let read_bytes = reader.read(&mut chunk).await?;
+        if read_bytes == 0 {
+            break;
+        }
+        buffer.extend_from_slice(&chunk[..read_bytes]);
+
+        while let Some(delimiter_pos) = buffer.iter().position(|&b| b == b'\r' || b == b'\n') {
+            let record: Vec<u8> = buffer.drain(..=delimiter_pos).collect();
+            if record[delimiter_pos] == b'\r' && buffer.first() == Some(&b'\n') {
+                buffer.remove(0);
+            }
+
+            let line = String::from_utf8_lossy(&record[..delimiter_pos]);
+            let line = line.trim();
+            if line.is_empty() {
+                // キープアライブ用の空行
+                continue;
+            }
+
+            match from_str::<Value>(line) {
+                Ok(value) => {
+                    println!("{}", value);
+                    std::io::Write::flush(&mut std::io::stdout())?;
+                }
+                Err(e) => warn!("Failed to parse streamed frame, skipping: {}", e),
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// メディアアップロード用の 1 リクエストを OAuth1 署名つきで送信し、レスポンスボディを返す。
+///
+/// 一般リクエスト (`send`) がクエリ文字列で送るのと違い、パラメーターは
+/// `application/x-www-form-urlencoded` のボディとして送る。フォームボディは OAuth1 の仕様上、署名ベース文字列に含める必要が
+/// あるので、`query_params` としてそのまま `sign_request` に渡す。
+async fn upload_request(
+    url: &str,
+    params: &BTreeMap<Box<str>, Value>,
+    environments: &Environments,
+) -> AnyResult<String> {
+    let (access_token, access_token_secret) = environments.require_access_token()?;
+    let oauth_params = sign_request(
+        Method::Post,
+        url,
+        params,
+        &[],
+        &environments.consumer_key,
+        &environments.consumer_secret,
+        access_token,
+        access_token_secret,
+    )?;
+
+    let body: Vec<_> = params
+        .iter()
+        .map(|(k, v)| format!("{}={}", k, format_param_value(v)))
+        .collect();
+
+    let mut response = surf::post(url)
+        .header("Authorization", oauth_header(&oauth_params))
+        .content_type("application/x-www-form-urlencoded")
+        .body_string(body.join("&"))
+        .await?;
+    response.body_string().await.map_err(Into::into)
+}
+
+/// 拡張子からおおよその media_type を推測する。
+fn guess_media_type(path: &str) -> &'static str {
+    match std::path::Path::new(path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_ascii_lowercase())
+        .as_deref()
+    {
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("mp4") => "video/mp4",
+        Some("mov") => "video/quicktime",
+        _ => "application/octet-stream",
+    }
+}
+
+/// `upload.twitter.com` に対してチャンク分割アップロード (INIT/APPEND/FINALIZE) を行い、
+/// `media_id_string` を返す。
+async fn upload_media(path: &str, environments: &Environments) -> AnyResult<String> {
+    const UPLOAD_URL: &str = "https://upload.twitter.com/1.1/media/upload.json";
+    const CHUNK_SIZE: usize = 1024 * 1024;
+
+    let total_bytes = async_std::fs::metadata(path).await?.len();
+    let media_type = guess_media_type(path);
+
+    // INIT
+    let mut init_params = BTreeMap::new();
+    init_params.insert("command".into(), "INIT".into());
+    init_params.insert("total_bytes".into(), total_bytes.into());
+    init_params.insert("media_type".into(), media_type.into());
+    let init_body = upload_request(UPLOAD_URL, &init_params, environments).await?;
+    let media_id = from_str::<Value>(&init_body)
+        .ok()
+        .and_then(|v| v["media_id_string"].as_str().map(str::to_owned))
+        .ok_or("INIT response did not contain media_id_string")?;
+
+    // APPEND
+    let mut reader = BufReader::new(File::open(path).await?);
+    let mut buffer = vec![0u8; CHUNK_SIZE];
+    let mut segment_index: u32 = 0;
+    loop {
+        let read_bytes = reader.read(&mut buffer).await?;
+        if read_bytes == 0 {
+            break;
+        }
+
+        let mut append_params = BTreeMap::new();
+        append_params.insert("command".into(), "APPEND".into());
+        append_params.insert("media_id".into(), media_id.clone().into());
+        append_params.insert("segment_index".into(), segment_index.into());
+        append_params.insert(
+            "media_data".into(),
+            base64_encode(&buffer[..read_bytes]).into(),
+        );
+        upload_request(UPLOAD_URL, &append_params, environments).await?;
+        segment_index += 1;
+    }
+
+    // FINALIZE
+    let mut finalize_params = BTreeMap::new();
+    finalize_params.insert("command".into(), "FINALIZE".into());
+    finalize_params.insert("media_id".into(), media_id.clone().into());
+    upload_request(UPLOAD_URL, &finalize_params, environments).await?;
+
+    Ok(media_id)
+}
+
+/// `send` サブコマンド: テンプレートファイルに従ってリクエストを送信する。
+async fn send(arguments: SendArguments) -> AnyResult<()> {
+    let environments: Environments = from_env().map_err(|e| {
+        error!("Failed to gather Twitter API key information: {}", e);
+        e
+    })?;
+    debug!("Consumer Key: {}", environments.consumer_key);
+    debug!("Consumer Secret: {}", environments.consumer_secret);
+    debug!("Access Token: {:?}", environments.access_token);
+    debug!("Access Token Secret: {:?}", environments.access_token_secret);
+
+    let template: Template = {
+        let mut reader = BufReader::new(File::open(&arguments.template_file).await?);
+        let mut json = String::with_capacity(8192);
+        reader.read_to_string(&mut json).await?;
+        from_str(&json).map_err(|e| {
+            error!("Failed to parse template file: {}", e);
+            e
+        })?
+    };
+
+    // 通常パラメーターの生成
+    let mut request_params = template.parameters.clone();
+    for op in &arguments.parameters {
+        let kv: Vec<_> = op.split('=').take(2).collect();
+        match (kv.get(0), kv.get(1)) {
+            (Some(&k), Some(&v)) if k != "" => {
+                request_params.insert(k.into(), v.into());
+            }
+            _ => {
+                warn!("Invalid parameter override detected, skipping...");
+            }
+        }
+    }
+
+    // メディアのアップロード
+    if !arguments.media.is_empty() {
+        let mut media_ids = Vec::with_capacity(arguments.media.len());
+        for path in &arguments.media {
+            media_ids.push(upload_media(path, &environments).await?);
+        }
+        request_params.insert("media_ids".into(), media_ids.join(",").into());
+    }
+
+    // エンドポイント
+    let endpoint_url = build_endpoint_url(&template);
+
+    // JSON ボディは POST/PUT でのみ意味を持ち、OAuth1 の署名ベース文字列には含めない
+    let use_json_body =
+        template.body == Body::Json && matches!(template.method, Method::Post | Method::Put);
+
+    // クエリ文字列として送られ、OAuth1 の署名ベース文字列にも含まれるパラメーター。
+    // `body: query` では `parameters` もクエリ文字列になるのでここに合流させる。
+    let mut query_params = template.query_parameters.clone();
+    if !use_json_body {
+        for (key, value) in &request_params {
+            query_params.insert(key.clone(), value.clone());
+        }
+    }
+
+    // Authorization ヘッダーの生成
+    let authorization_header = match template.auth {
+        Auth::OAuth1 => {
+            let (access_token, access_token_secret) = environments.require_access_token()?;
+            let oauth_params = sign_request(
+                template.method,
+                &endpoint_url,
+                &query_params,
+                &[],
+                &environments.consumer_key,
+                &environments.consumer_secret,
+                access_token,
+                access_token_secret,
+            )?;
+            oauth_header(&oauth_params)
+        }
+        Auth::Bearer => {
+            let token =
+                fetch_bearer_token(&environments.consumer_key, &environments.consumer_secret)
+                    .await?;
+            eprintln!("Bearer token: {}", token);
+
+            format!("Bearer {}", token)
+        }
+    };
+
+    debug!("General parameters");
+    for (key, value) in &request_params {
+        debug!("{}: {}", key, value);
+    }
 
     // 送信
     let request = match template.method {
@@ -247,18 +633,123 @@ async fn main() -> AnyResult<()> {
         Method::Put => surf::put(endpoint_url),
         Method::Delete => surf::delete(endpoint_url),
     }
-    .header(
-        "Authorization",
-        format!("OAuth {}", oauth_header.join(", ")),
-    );
+    .header("Authorization", authorization_header);
 
-    let mut response = if request_params.is_empty() {
-        request.await?
+    let request = if query_params.is_empty() {
+        request
     } else {
-        request.query(&request_params)?.await?
+        request.query(&query_params)?
     };
-    let body = response.body_string().await?;
 
-    println!("{}", body);
+    let response = if use_json_body {
+        let json_body: Value = request_params
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.clone()))
+            .collect::<serde_json::Map<_, _>>()
+            .into();
+        request.body_json(&json_body)?.await?
+    } else {
+        request.await?
+    };
+
+    if template.stream {
+        stream_response(response).await?;
+    } else {
+        let mut response = response;
+        let body = response.body_string().await?;
+        println!("{}", body);
+    }
+    Ok(())
+}
+
+/// `auth` サブコマンド: 3-legged PIN ベースの OAuth フローでアクセストークンを取得し、
+/// `.env` に書き込む。
+async fn auth() -> AnyResult<()> {
+    let environments: ConsumerEnvironments = from_env().map_err(|e| {
+        error!("Failed to gather Twitter API key information: {}", e);
+        e
+    })?;
+
+    // Step 1: リクエストトークンの取得
+    let request_token_url = "https://api.twitter.com/oauth/request_token";
+    let oauth_params = sign_request(
+        Method::Post,
+        request_token_url,
+        &BTreeMap::new(),
+        &[("oauth_callback", "oob".to_owned())],
+        &environments.consumer_key,
+        &environments.consumer_secret,
+        "",
+        "",
+    )?;
+    let mut response = surf::post(request_token_url)
+        .header("Authorization", oauth_header(&oauth_params))
+        .await?;
+    let body = response.body_string().await?;
+    let request_token_params = parse_form_encoded(&body);
+    let request_token = request_token_params
+        .get("oauth_token")
+        .ok_or("Request token response did not contain oauth_token")?
+        .to_owned();
+    let request_token_secret = request_token_params
+        .get("oauth_token_secret")
+        .ok_or("Request token response did not contain oauth_token_secret")?
+        .to_owned();
+
+    println!(
+        "Open the following URL, authorize the app, and enter the PIN it shows:\nhttps://api.twitter.com/oauth/authorize?oauth_token={}",
+        request_token
+    );
+    print!("PIN: ");
+    std::io::Write::flush(&mut std::io::stdout())?;
+    let mut pin = String::new();
+    stdin().read_line(&mut pin).await?;
+    let pin = pin.trim();
+
+    // Step 2: アクセストークンの取得
+    let access_token_url = "https://api.twitter.com/oauth/access_token";
+    let oauth_params = sign_request(
+        Method::Post,
+        access_token_url,
+        &BTreeMap::new(),
+        &[("oauth_verifier", pin.to_owned())],
+        &environments.consumer_key,
+        &environments.consumer_secret,
+        &request_token,
+        &request_token_secret,
+    )?;
+    let mut response = surf::post(access_token_url)
+        .header("Authorization", oauth_header(&oauth_params))
+        .await?;
+    let body = response.body_string().await?;
+    let access_token_params = parse_form_encoded(&body);
+    let access_token = access_token_params
+        .get("oauth_token")
+        .ok_or("Access token response did not contain oauth_token")?;
+    let access_token_secret = access_token_params
+        .get("oauth_token_secret")
+        .ok_or("Access token response did not contain oauth_token_secret")?;
+
+    let mut env_file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(".env")
+        .await?;
+    env_file
+        .write_all(format!("TWITTER_AT={}\nTWITTER_ATS={}\n", access_token, access_token_secret).as_bytes())
+        .await?;
+
+    println!("Wrote TWITTER_AT/TWITTER_ATS to .env");
     Ok(())
 }
+
+#[async_std::main]
+async fn main() -> AnyResult<()> {
+    pretty_env_logger::init();
+    dotenv().ok();
+
+    match Arguments::parse() {
+        Arguments::Send(arguments) => send(arguments).await,
+        Arguments::Auth => auth().await,
+    }
+}